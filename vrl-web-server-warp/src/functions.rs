@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::convert::Infallible;
+use warp::{reply::json, Reply};
+
+use crate::resolve::custom_vrl_functions;
+
+// One parameter a function accepts, as declared on `vrl::Function::parameters()`.
+#[derive(Serialize)]
+pub(crate) struct ParameterInfo {
+    name: &'static str,
+    kind: String,
+    required: bool,
+}
+
+// Everything the web editor needs to drive autocomplete and signature help
+// for a single stdlib or custom function, without hardcoding the function
+// set client-side.
+#[derive(Serialize)]
+pub(crate) struct FunctionInfo {
+    identifier: &'static str,
+    summary: &'static str,
+    usage: &'static str,
+    parameters: Vec<ParameterInfo>,
+}
+
+impl From<&dyn vrl::Function> for FunctionInfo {
+    fn from(function: &dyn vrl::Function) -> Self {
+        let parameters = function
+            .parameters()
+            .iter()
+            .map(|parameter| ParameterInfo {
+                name: parameter.keyword,
+                kind: vrl::value::Kind::new(parameter.kind).to_string(),
+                required: parameter.required,
+            })
+            .collect();
+
+        FunctionInfo {
+            identifier: function.identifier(),
+            summary: function.summary(),
+            usage: function.usage(),
+            parameters,
+        }
+    }
+}
+
+// Enumerates every function resolvable by `/resolve` and `/resolve/suite`:
+// the full VRL stdlib plus this server's custom additions (e.g.
+// `BitwiseAnd`), so the frontend never has to hardcode the function set.
+fn list_functions() -> Vec<FunctionInfo> {
+    let mut functions = vrl_stdlib::all();
+    functions.append(&mut custom_vrl_functions());
+
+    functions
+        .iter()
+        .map(|function| FunctionInfo::from(function.as_ref()))
+        .collect()
+}
+
+// The function-introspection logic as an HTTP handler
+pub(crate) async fn list_vrl_functions() -> Result<impl Reply, Infallible> {
+    Ok(json(&list_functions()))
+}
+
+// The cache-stats logic as an HTTP handler
+pub(crate) async fn vrl_cache_stats() -> Result<impl Reply, Infallible> {
+    Ok(json(&crate::resolve::cache_stats()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resolve::custom_vrl_functions;
+    use crate::server::router;
+    use http::StatusCode;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_functions_enumerates_stdlib_plus_custom_additions() {
+        let res = warp::test::request()
+            .method("GET")
+            .path("/functions")
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let functions: Vec<Value> = serde_json::from_slice(res.body()).unwrap();
+
+        assert_eq!(
+            functions.len(),
+            vrl_stdlib::all().len() + custom_vrl_functions().len()
+        );
+        assert!(functions
+            .iter()
+            .any(|function| function["identifier"] == "bitwise_and"));
+    }
+
+    #[tokio::test]
+    async fn test_stats_reflects_cache_hits_and_misses() {
+        // A program this test hasn't compiled before, so the first /resolve
+        // is guaranteed to be a miss and the second a hit.
+        let program = r#".stats_probe = "only used by test_stats_reflects_cache_hits_and_misses""#;
+
+        let before = warp::test::request()
+            .method("GET")
+            .path("/stats")
+            .reply(&router())
+            .await;
+        let before: Value = serde_json::from_slice(before.body()).unwrap();
+
+        for _ in 0..2 {
+            let res = warp::test::request()
+                .method("POST")
+                .path("/resolve")
+                .json(&serde_json::json!({"program": program}))
+                .reply(&router())
+                .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let after = warp::test::request()
+            .method("GET")
+            .path("/stats")
+            .reply(&router())
+            .await;
+        assert_eq!(after.status(), StatusCode::OK);
+        let after: Value = serde_json::from_slice(after.body()).unwrap();
+
+        assert!(after["misses"].as_u64().unwrap() > before["misses"].as_u64().unwrap());
+        assert!(after["hits"].as_u64().unwrap() > before["hits"].as_u64().unwrap());
+    }
+}
@@ -0,0 +1,6 @@
+mod bit_and;
+mod functions;
+mod resolve;
+mod server;
+mod share;
+mod suite;
@@ -3,13 +3,16 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::convert::Infallible;
 use vector_common::TimeZone;
-use vrl::{diagnostic::Formatter, state, value, Program, Runtime, TargetValueRef};
+use vrl::{
+    diagnostic::{DiagnosticMessage, Severity},
+    state, value, Program, Runtime, TargetValueRef,
+};
 use warp::{reply::json, Reply};
 
-use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
 use lru::LruCache;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::{cell::RefCell, time::Instant};
@@ -25,87 +28,247 @@ pub fn custom_vrl_functions() -> Vec<Box<dyn vrl::Function>> {
     ]
 }
 
-// The VRL program plus (optional) event plus (optional) time zone
-#[derive(Deserialize, Serialize)]
+// The VRL program plus (optional) event plus (optional) time zone. If
+// `events` is given, the program is run once per event (sharing a single
+// compilation) and the response is a `Vec<Outcome>` instead of one
+// `Outcome`; `event` is ignored in that case.
+#[derive(Clone, Deserialize, Serialize)]
 pub(crate) struct Input {
     program: String,
     event: Option<Value>,
+    events: Option<Vec<Value>>,
     tz: Option<String>,
 }
 
 // An enum for the result of a VRL resolution operation
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
-enum Outcome {
-    Success { output: Value, result: Value },
+pub(crate) enum Outcome {
+    // `warnings` carries any non-fatal compiler diagnostics (e.g. unused
+    // variables), surfaced the same way compile errors are so a web editor
+    // can render them instead of only seeing them in the server log.
+    Success {
+        output: Value,
+        result: Value,
+        warnings: Vec<Diagnostic>,
+    },
+    // A program that failed to *compile*. Carries one `Diagnostic` per error
+    // or warning so a web editor can draw inline squiggles instead of parsing
+    // a formatted string.
+    CompileError {
+        diagnostics: Vec<Diagnostic>,
+    },
+    // A program that compiled fine but failed while *running* against the
+    // given event.
     Error(String),
+    // The request itself was malformed in a way that never reaches
+    // compilation or execution, e.g. an unparseable `tz`.
+    InputError(String),
 }
 
-// The VRL resolution logic
-fn resolve(input: Input) -> Outcome {
-    lazy_static! {
-        static ref CACHE: Arc<Mutex<LruCache<String, Result<Program, String>>>> = Arc::new(
-            Mutex::new(LruCache::new(std::num::NonZeroUsize::new(400).unwrap()))
-        );
-    };
+// The severity of a single `Diagnostic`, a reduced form of
+// `vrl::diagnostic::Severity`: its internal `Bug` variant is reported as
+// `Error` and its `Note` variant as `Warning`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+// A byte-offset span into the source program, as returned by VRL's
+// diagnostic labels.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct SourceSpan {
+    start: usize,
+    end: usize,
+}
+
+// A single annotation VRL wants drawn somewhere in the source, e.g. under the
+// offending expression.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct Label {
+    message: String,
+    primary: bool,
+    span: SourceSpan,
+}
+
+// A serializable view of one of VRL's compiler diagnostics (error or
+// warning), built directly from a `&dyn DiagnosticMessage` rather than
+// flattened into a string by `Formatter`.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct Diagnostic {
+    severity: DiagnosticSeverity,
+    message: String,
+    code: Option<String>,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    fn from_message(diagnostic: &dyn DiagnosticMessage) -> Self {
+        let severity = match diagnostic.severity() {
+            Severity::Bug | Severity::Error => DiagnosticSeverity::Error,
+            Severity::Warning | Severity::Note => DiagnosticSeverity::Warning,
+        };
+
+        let labels = diagnostic
+            .labels()
+            .into_iter()
+            .map(|label| Label {
+                message: label.message,
+                primary: label.primary,
+                span: SourceSpan {
+                    start: label.span.start(),
+                    end: label.span.end(),
+                },
+            })
+            .collect();
+
+        let notes = diagnostic
+            .notes()
+            .into_iter()
+            .map(|note| note.to_string())
+            .collect();
+
+        Diagnostic {
+            severity,
+            message: diagnostic.message(),
+            code: Some(format!("E{:03}", diagnostic.code())),
+            labels,
+            notes,
+        }
+    }
+
+    fn from_diagnostics(diagnostics: &[Box<dyn DiagnosticMessage>]) -> Vec<Diagnostic> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| Diagnostic::from_message(diagnostic.as_ref()))
+            .collect()
+    }
+}
+
+// A successfully compiled program plus any non-fatal warnings the compiler
+// produced along with it (e.g. unused variables). Cheap to clone: the
+// program is behind an `Arc` and the warning list is typically empty.
+#[derive(Clone)]
+pub(crate) struct Compiled {
+    pub(crate) program: Arc<Program>,
+    pub(crate) warnings: Vec<Diagnostic>,
+}
+
+lazy_static! {
+    // Compiled programs are cheap to clone (`Arc`) and safe to share across
+    // worker threads, so the mutex is held only for cache lookup and
+    // compilation, never for the (potentially slow) `Runtime::resolve` call
+    // below.
+    static ref CACHE: Arc<Mutex<LruCache<String, Result<Compiled, Vec<Diagnostic>>>>> = Arc::new(
+        Mutex::new(LruCache::new(std::num::NonZeroUsize::new(400).unwrap()))
+    );
+}
 
-    let mut value: Value = input.event.unwrap_or(value!({}));
-    let program = input.program.as_str();
+// Coarse counters so operators can observe the cache from `/stats` without
+// instrumenting anything external. `Relaxed` is fine: these are independent
+// counters, not used to synchronize access to anything else.
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+static LAST_COMPILE_MICROS: AtomicU64 = AtomicU64::new(0);
+
+// A snapshot of the compile cache's size and hit/miss counters, for the
+// `/stats` endpoint.
+#[derive(Serialize)]
+pub(crate) struct CacheStats {
+    size: usize,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    last_compile_micros: u64,
+}
+
+pub(crate) fn cache_stats() -> CacheStats {
+    let cache_ref = CACHE.lock().unwrap();
+    CacheStats {
+        size: (*cache_ref).len(),
+        capacity: (*cache_ref).cap().get(),
+        hits: CACHE_HITS.load(Ordering::Relaxed),
+        misses: CACHE_MISSES.load(Ordering::Relaxed),
+        last_compile_micros: LAST_COMPILE_MICROS.load(Ordering::Relaxed),
+    }
+}
 
+// Compiles `program` if it isn't already cached, returning a cheaply cloned
+// handle to the result. The returned `Compiled`/`Vec<Diagnostic>` are
+// independent of the cache's `MutexGuard`, so callers never need to hold the
+// lock past this call.
+pub(crate) fn compile_cached(program: &str) -> Result<Compiled, Vec<Diagnostic>> {
     let mut cache_ref = CACHE.lock().unwrap();
 
-    let stored_result = (*cache_ref).get(program);
+    if let Some(compiled) = (*cache_ref).get(program) {
+        CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+        return compiled.clone();
+    }
+
+    CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
 
     let mut functions = vrl_stdlib::all();
     functions.append(&mut custom_vrl_functions());
 
     let start = Instant::now();
-    let compiled = match stored_result {
-        Some(compiled) => match compiled {
-            Ok(compiled) => Ok(compiled),
-            Err(e) => {
-                return Outcome::Error(e.clone());
-            }
-        },
-        None => match vrl::compile(program, &functions) {
-            Ok(result) => {
-                debug!(
-                    "Compiled a vrl program ({}), took {:?}",
-                    program
-                        .lines()
-                        .into_iter()
-                        .skip(1)
-                        .next()
-                        .unwrap_or("expansion"),
-                    start.elapsed()
-                );
-                (*cache_ref).put(program.to_string(), Ok(result.program));
-                if result.warnings.len() > 0 {
-                    warn!("{:?}", result.warnings);
-                }
-                match (*cache_ref).get(program) {
-                    Some(compiled) => match compiled {
-                        Ok(compiled) => Ok(compiled),
-                        Err(e) => {
-                            return Outcome::Error(e.clone());
-                        }
-                    },
-                    None => unreachable!(),
-                }
-            }
-            Err(diagnostics) => {
-                let msg = Formatter::new(&program, diagnostics).to_string();
-                (*cache_ref).put(program.to_string(), Err(msg.clone()));
-                Err(anyhow!(msg))
+    let result = match vrl::compile(program, &functions) {
+        Ok(result) => {
+            debug!(
+                "Compiled a vrl program ({}), took {:?}",
+                program
+                    .lines()
+                    .into_iter()
+                    .skip(1)
+                    .next()
+                    .unwrap_or("expansion"),
+                start.elapsed()
+            );
+            let warnings = Diagnostic::from_diagnostics(&result.warnings);
+            if !warnings.is_empty() {
+                warn!("{:?}", result.warnings);
             }
-        },
+            Ok(Compiled {
+                program: Arc::new(result.program),
+                warnings,
+            })
+        }
+        Err(diagnostics) => Err(Diagnostic::from_diagnostics(&diagnostics)),
     };
+    LAST_COMPILE_MICROS.store(start.elapsed().as_micros() as u64, Ordering::Relaxed);
 
-    if compiled.is_err() {
-        return Outcome::Error(compiled.err().unwrap().to_string());
+    (*cache_ref).put(program.to_string(), result.clone());
+    result
+}
+
+// Parses the `tz` field of an `Input`/`Case`, defaulting to UTC when absent
+// and reporting an unparseable zone as an `Outcome::InputError`-shaped
+// error rather than silently falling back to the server's local time zone.
+pub(crate) fn resolve_time_zone(tz: &Option<String>) -> Result<TimeZone, String> {
+    match tz {
+        Some(tz) => {
+            TimeZone::parse(tz).ok_or_else(|| format!("unrecognized time zone: {:?}", tz))
+        }
+        None => Ok(TimeZone::parse("UTC").expect("UTC is always a valid time zone")),
     }
-    let compiled = compiled.unwrap();
+}
 
+// Runs an already-compiled program against a single event on the
+// thread-local `Runtime`. Shared by the `/resolve` handler and anything else
+// (e.g. the test-suite runner) that wants to reuse a compiled program across
+// many events without paying for recompilation. `warnings` are the
+// compiler's non-fatal diagnostics for `compiled`, forwarded onto a
+// successful `Outcome::Success` so the caller doesn't have to recompile to
+// see them.
+pub(crate) fn resolve_compiled(
+    compiled: &Program,
+    event: Value,
+    time_zone: &TimeZone,
+    warnings: Vec<Diagnostic>,
+) -> Outcome {
+    let mut value = event;
     let mut metadata = ::value::Value::Object(BTreeMap::new());
     let mut secrets = ::value::Secrets::new();
     let mut target = TargetValueRef {
@@ -114,35 +277,72 @@ fn resolve(input: Input) -> Outcome {
         secrets: &mut secrets,
     };
 
-    let time_zone_str = Some("tt".to_string()).unwrap_or_default();
-
-    let time_zone = match TimeZone::parse(&time_zone_str) {
-        Some(tz) => tz,
-        None => TimeZone::Local,
-    };
-
     let result = RUNTIME.with(|r| {
         let mut runtime = r.borrow_mut();
 
-        match (*runtime).resolve(&mut target, &compiled, &time_zone) {
+        match (*runtime).resolve(&mut target, compiled, time_zone) {
             Ok(result) => Ok(result),
             Err(err) => Err(err.to_string()),
         }
     });
 
-    let res = match result {
+    match result {
         Ok(result) => Outcome::Success {
             output: result,
             result: value,
+            warnings,
         },
         Err(err) => Outcome::Error(err),
+    }
+}
+
+// The VRL resolution logic
+fn resolve(input: Input) -> Outcome {
+    let event: Value = input.event.unwrap_or(value!({}));
+
+    let time_zone = match resolve_time_zone(&input.tz) {
+        Ok(time_zone) => time_zone,
+        Err(err) => return Outcome::InputError(err),
     };
 
-    res
+    let compiled = match compile_cached(input.program.as_str()) {
+        Ok(compiled) => compiled,
+        Err(diagnostics) => return Outcome::CompileError { diagnostics },
+    };
+
+    resolve_compiled(&compiled.program, event, &time_zone, compiled.warnings)
+}
+
+// Like `resolve`, but maps the compiled program over every event in
+// `input.events`, matching how VRL is actually used as a transform over an
+// event stream rather than one value at a time. Compilation and time zone
+// parsing happen once, up front, and are shared across every event.
+fn resolve_many(input: Input, events: Vec<Value>) -> Vec<Outcome> {
+    let time_zone = match resolve_time_zone(&input.tz) {
+        Ok(time_zone) => time_zone,
+        Err(err) => return vec![Outcome::InputError(err)],
+    };
+
+    let compiled = match compile_cached(input.program.as_str()) {
+        Ok(compiled) => compiled,
+        Err(diagnostics) => return vec![Outcome::CompileError { diagnostics }],
+    };
+
+    events
+        .into_iter()
+        .map(|event| {
+            resolve_compiled(&compiled.program, event, &time_zone, compiled.warnings.clone())
+        })
+        .collect()
 }
 
 // The VRL resolution logic as an HTTP handler
-pub(crate) async fn resolve_vrl_input(input: Input) -> Result<impl Reply, Infallible> {
+pub(crate) async fn resolve_vrl_input(mut input: Input) -> Result<impl Reply, Infallible> {
+    if let Some(events) = input.events.take() {
+        let outcomes = resolve_many(input, events);
+        return Ok(json(&outcomes));
+    }
+
     let outcome = resolve(input);
     Ok(json(&outcome))
 }
@@ -173,22 +373,26 @@ mod tests {
                 Input {
                     program: r#".foo = "bar""#.to_owned(),
                     event: None,
+                    events: None,
                     tz: None,
                 },
                 Outcome::Success {
                     result: value!({"foo": "bar"}),
                     output: value!("bar"),
+                    warnings: vec![],
                 },
             ),
             (
                 Input {
                     program: r#".tags.environment = "production"; del(.delete_me)"#.to_owned(),
                     event: Some(value!({"delete_me": "bye bye"})),
+                    events: None,
                     tz: None,
                 },
                 Outcome::Success {
                     result: value!({"tags": {"environment": "production"}}),
                     output: value!("bye bye"),
+                    warnings: vec![],
                 },
             ),
         ];
@@ -224,4 +428,95 @@ mod tests {
             assert_eq!(res.status(), StatusCode::BAD_REQUEST);
         }
     }
+
+    #[tokio::test]
+    async fn test_compile_error_has_structured_diagnostics() {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/resolve")
+            .json(&Input {
+                program: r#"*** not valid vrl ***"#.to_owned(),
+                event: None,
+                events: None,
+                tz: None,
+            })
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let outcome: Outcome = serde_json::from_slice(res.body()).unwrap();
+        match outcome {
+            Outcome::CompileError { diagnostics } => {
+                assert!(!diagnostics.is_empty());
+                assert!(diagnostics.iter().any(|d| !d.labels.is_empty()));
+            }
+            _ => panic!("expected a CompileError outcome"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_tz_returns_input_error() {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/resolve")
+            .json(&Input {
+                program: r#".foo = "bar""#.to_owned(),
+                event: None,
+                events: None,
+                tz: Some("Not/A_Real_Zone".to_owned()),
+            })
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let outcome: Outcome = serde_json::from_slice(res.body()).unwrap();
+        assert!(matches!(outcome, Outcome::InputError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_batched_events_returns_one_outcome_per_event() {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/resolve")
+            .json(&Input {
+                program: r#".foo = "bar""#.to_owned(),
+                event: None,
+                events: Some(vec![value!({}), value!({}), value!({})]),
+                tz: None,
+            })
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let outcomes: Vec<Outcome> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, Outcome::Success { .. })));
+    }
+
+    #[test]
+    fn test_compile_cached_is_consistent_under_concurrent_access() {
+        use std::thread;
+
+        // Regression test for dropping the cache lock before resolution:
+        // many threads hitting the same (and different) cache keys at once
+        // should never panic or disagree on whether a program compiles.
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                thread::spawn(move || {
+                    let program = if i % 2 == 0 {
+                        r#".foo = "bar""#
+                    } else {
+                        r#".baz = "qux""#
+                    };
+                    super::compile_cached(program).is_ok()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+    }
 }
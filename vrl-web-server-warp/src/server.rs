@@ -0,0 +1,80 @@
+use warp::{Filter, Reply};
+
+use crate::functions::{list_vrl_functions, vrl_cache_stats};
+use crate::resolve::resolve_vrl_input;
+use crate::share::{load_vrl_input, share_vrl_input};
+use crate::suite::{resolve_vrl_suite, Suite};
+
+// Boxes a handler's reply so routes with different concrete `Reply` types
+// can still be `.or()`-combined into one filter tree below.
+fn boxed_reply<F>(
+    filter: F,
+) -> warp::filters::BoxedFilter<(Box<dyn Reply>,)>
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+    F::Error: Into<warp::Rejection>,
+{
+    filter
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed()
+}
+
+// The full set of HTTP endpoints this server exposes.
+pub(crate) fn router() -> warp::filters::BoxedFilter<(Box<dyn Reply>,)> {
+    let resolve = boxed_reply(
+        warp::path("resolve")
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and_then(resolve_vrl_input),
+    );
+
+    let suite = boxed_reply(
+        warp::path!("resolve" / "suite")
+            .and(warp::post())
+            .and(warp::body::json::<Suite>())
+            .and_then(resolve_vrl_suite),
+    );
+
+    let functions = boxed_reply(
+        warp::path("functions")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and_then(list_vrl_functions),
+    );
+
+    let stats = boxed_reply(
+        warp::path("stats")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and_then(vrl_cache_stats),
+    );
+
+    let share = boxed_reply(
+        warp::path("share")
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and_then(share_vrl_input),
+    );
+
+    let load_share = boxed_reply(
+        warp::path!("share" / String)
+            .and(warp::get())
+            .and_then(load_vrl_input),
+    );
+
+    resolve
+        .or(suite)
+        .unify()
+        .or(functions)
+        .unify()
+        .or(stats)
+        .unify()
+        .or(share)
+        .unify()
+        .or(load_share)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,204 @@
+use lazy_static::lazy_static;
+use log::error;
+use lru::LruCache;
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use warp::{
+    http::StatusCode,
+    reply::{json as reply_json, with_status},
+    Reply,
+};
+
+use crate::resolve::Input;
+
+pub(crate) type SnippetId = String;
+
+// Anything that can durably hold `Input`s behind a short opaque ID. The
+// default implementation (`InMemorySnippetStore`) is good enough for a
+// single playground instance; a deployment that runs several instances
+// behind a load balancer would back this with an external key-value
+// service instead (see `HttpSnippetStore` below).
+//
+// `put` returns `Err` on a backend failure (e.g. a network error or a
+// non-2xx response from an HTTP-backed store) rather than reporting success
+// with an ID that was never actually durably stored.
+pub(crate) trait SnippetStore: Send + Sync {
+    fn put(&self, input: Input) -> Result<SnippetId, String>;
+    fn get(&self, id: &SnippetId) -> Option<Input>;
+}
+
+// Derives a short, stable ID from the serialized content of an `Input`, so
+// sharing the same snippet twice returns the same link instead of growing
+// the store.
+fn content_id(input: &Input) -> SnippetId {
+    let serialized = serde_json::to_string(input).expect("Input always serializes");
+    let mut hasher = DefaultHasher::new();
+    serialized.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+// The default `SnippetStore`: an LRU-bounded in-memory map, scoped to this
+// process.
+pub(crate) struct InMemorySnippetStore {
+    cache: Mutex<LruCache<SnippetId, Input>>,
+}
+
+impl InMemorySnippetStore {
+    fn new(capacity: NonZeroUsize) -> Self {
+        InMemorySnippetStore {
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+impl SnippetStore for InMemorySnippetStore {
+    fn put(&self, input: Input) -> Result<SnippetId, String> {
+        let id = content_id(&input);
+        self.cache.lock().unwrap().put(id.clone(), input);
+        Ok(id)
+    }
+
+    fn get(&self, id: &SnippetId) -> Option<Input> {
+        self.cache.lock().unwrap().get(id).cloned()
+    }
+}
+
+// A `SnippetStore` backed by PUT/GET against an external S3/K2V-style
+// bucket, for deployments that share snippets across more than one server
+// process. Requests are sent unsigned, so this only works against a bucket
+// configured to accept anonymous writes/reads (e.g. behind a trusted
+// network boundary) — wiring in real request signing (SigV4 or whatever
+// the target backend requires) is left for when this is pointed at a real
+// bucket. Gated behind a feature flag since it needs an HTTP client that an
+// in-process playground doesn't.
+#[cfg(feature = "http-store")]
+pub(crate) struct HttpSnippetStore {
+    endpoint: String,
+    bucket: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "http-store")]
+impl HttpSnippetStore {
+    pub(crate) fn new(endpoint: String, bucket: String) -> Self {
+        HttpSnippetStore {
+            endpoint,
+            bucket,
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    fn object_url(&self, id: &SnippetId) -> String {
+        format!("{}/{}/{}", self.endpoint, self.bucket, id)
+    }
+}
+
+#[cfg(feature = "http-store")]
+impl SnippetStore for HttpSnippetStore {
+    fn put(&self, input: Input) -> Result<SnippetId, String> {
+        let id = content_id(&input);
+        let body = serde_json::to_vec(&input).expect("Input always serializes");
+
+        let response = self
+            .client
+            .put(self.object_url(&id))
+            .body(body)
+            .send()
+            .map_err(|err| format!("PUT to snippet store failed: {}", err))?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "snippet store rejected PUT with status {}",
+                response.status()
+            ));
+        }
+
+        Ok(id)
+    }
+
+    fn get(&self, id: &SnippetId) -> Option<Input> {
+        let response = self.client.get(self.object_url(id)).send().ok()?;
+        response.json().ok()
+    }
+}
+
+lazy_static! {
+    static ref STORE: Box<dyn SnippetStore> =
+        Box::new(InMemorySnippetStore::new(NonZeroUsize::new(4096).unwrap()));
+}
+
+// The snippet-persistence logic as an HTTP handler: POST /share
+pub(crate) async fn share_vrl_input(input: Input) -> Result<Box<dyn Reply>, Infallible> {
+    match STORE.put(input) {
+        Ok(id) => Ok(Box::new(reply_json(&json!({ "id": id })))),
+        Err(err) => {
+            error!("failed to persist shared snippet: {}", err);
+            Ok(Box::new(with_status(
+                reply_json(&json!({ "error": "failed to save snippet" })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
+
+// The snippet-lookup logic as an HTTP handler: GET /share/{id}
+pub(crate) async fn load_vrl_input(id: SnippetId) -> Result<Box<dyn Reply>, Infallible> {
+    match STORE.get(&id) {
+        Some(input) => Ok(Box::new(reply_json(&input))),
+        None => Ok(Box::new(with_status(
+            reply_json(&json!({ "error": "no such snippet" })),
+            StatusCode::NOT_FOUND,
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::router;
+    use http::StatusCode;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_share_and_load_round_trip() {
+        let input = json!({
+            "program": r#".foo = "bar""#,
+            "event": null,
+            "events": null,
+            "tz": null,
+        });
+
+        let share_res = warp::test::request()
+            .method("POST")
+            .path("/share")
+            .json(&input)
+            .reply(&router())
+            .await;
+        assert_eq!(share_res.status(), StatusCode::OK);
+        let share_body: Value = serde_json::from_slice(share_res.body()).unwrap();
+        let id = share_body["id"].as_str().expect("response carries an id");
+
+        let load_res = warp::test::request()
+            .method("GET")
+            .path(&format!("/share/{}", id))
+            .reply(&router())
+            .await;
+        assert_eq!(load_res.status(), StatusCode::OK);
+        let loaded: Value = serde_json::from_slice(load_res.body()).unwrap();
+        assert_eq!(loaded["program"], input["program"]);
+    }
+
+    #[tokio::test]
+    async fn test_load_unknown_snippet_returns_404() {
+        let res = warp::test::request()
+            .method("GET")
+            .path("/share/this-id-was-never-shared")
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,199 @@
+use ::value::Value;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use vrl::value;
+use warp::{reply::json, Reply};
+
+use crate::resolve::{compile_cached, resolve_compiled, resolve_time_zone, Compiled, Outcome};
+
+// A single test case: an event to run the suite's program against, plus the
+// output/result it's expected to produce.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Case {
+    event: Option<Value>,
+    tz: Option<String>,
+    expect: Expectation,
+}
+
+// What a `Case` expects back from resolution. Either field may be omitted if
+// the case doesn't care about checking it.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Expectation {
+    result: Option<Value>,
+    output: Option<Value>,
+}
+
+// A program plus a batch of cases to run it against.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Suite {
+    program: String,
+    cases: Vec<Case>,
+}
+
+// Which expected fields diverged from the actual outcome, and what the
+// mismatch was.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Diff {
+    result: Option<Mismatch>,
+    output: Option<Mismatch>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Mismatch {
+    expected: Value,
+    actual: Value,
+}
+
+// The report for a single case: whether it passed, the outcome it actually
+// produced, and (if it failed on a value mismatch) a diff of the fields that
+// didn't match.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct CaseReport {
+    index: usize,
+    passed: bool,
+    actual: Outcome,
+    diff: Option<Diff>,
+}
+
+// Runs a case's event/tz through `compiled` and checks the outcome against
+// its expectation.
+fn run_case(compiled: &Compiled, index: usize, case: Case) -> CaseReport {
+    let time_zone = match resolve_time_zone(&case.tz) {
+        Ok(time_zone) => time_zone,
+        Err(err) => {
+            return CaseReport {
+                index,
+                passed: false,
+                actual: Outcome::InputError(err),
+                diff: None,
+            }
+        }
+    };
+
+    let actual = resolve_compiled(
+        &compiled.program,
+        case.event.unwrap_or(value!({})),
+        &time_zone,
+        compiled.warnings.clone(),
+    );
+
+    let (passed, diff) = match &actual {
+        Outcome::Success { output, result, .. } => {
+            let result_mismatch = match &case.expect.result {
+                Some(expected) if expected != result => Some(Mismatch {
+                    expected: expected.clone(),
+                    actual: result.clone(),
+                }),
+                _ => None,
+            };
+            let output_mismatch = match &case.expect.output {
+                Some(expected) if expected != output => Some(Mismatch {
+                    expected: expected.clone(),
+                    actual: output.clone(),
+                }),
+                _ => None,
+            };
+
+            if result_mismatch.is_none() && output_mismatch.is_none() {
+                (true, None)
+            } else {
+                (
+                    false,
+                    Some(Diff {
+                        result: result_mismatch,
+                        output: output_mismatch,
+                    }),
+                )
+            }
+        }
+        Outcome::CompileError { .. } | Outcome::Error(_) | Outcome::InputError(_) => (false, None),
+    };
+
+    CaseReport {
+        index,
+        passed,
+        actual,
+        diff,
+    }
+}
+
+// Compiles `suite.program` once and runs every case against it, so N cases
+// cost one compilation instead of N `/resolve` round-trips.
+fn run_suite(suite: Suite) -> Vec<CaseReport> {
+    let compiled = match compile_cached(&suite.program) {
+        Ok(compiled) => compiled,
+        Err(diagnostics) => {
+            let actual = Outcome::CompileError { diagnostics };
+            return suite
+                .cases
+                .into_iter()
+                .enumerate()
+                .map(|(index, _)| CaseReport {
+                    index,
+                    passed: false,
+                    actual: actual.clone(),
+                    diff: None,
+                })
+                .collect();
+        }
+    };
+
+    suite
+        .cases
+        .into_iter()
+        .enumerate()
+        .map(|(index, case)| run_case(&compiled, index, case))
+        .collect()
+}
+
+// The test-suite logic as an HTTP handler
+pub(crate) async fn resolve_vrl_suite(suite: Suite) -> Result<impl Reply, Infallible> {
+    let reports = run_suite(suite);
+    Ok(json(&reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CaseReport;
+    use crate::server::router;
+    use http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_suite_reports_per_case_pass_and_fail_with_diff() {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/resolve/suite")
+            .json(&json!({
+                "program": r#".foo = "bar""#,
+                "cases": [
+                    {
+                        "event": {},
+                        "tz": null,
+                        "expect": {"result": {"foo": "bar"}, "output": "bar"}
+                    },
+                    {
+                        "event": {},
+                        "tz": null,
+                        "expect": {"result": {"foo": "nope"}, "output": null}
+                    }
+                ]
+            }))
+            .reply(&router())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let reports: Vec<CaseReport> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(reports.len(), 2);
+
+        assert_eq!(reports[0].index, 0);
+        assert!(reports[0].passed);
+        assert!(reports[0].diff.is_none());
+
+        assert_eq!(reports[1].index, 1);
+        assert!(!reports[1].passed);
+        let diff = reports[1].diff.as_ref().expect("mismatch should produce a diff");
+        assert!(diff.result.is_some());
+        assert!(diff.output.is_none());
+    }
+}